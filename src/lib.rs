@@ -1,7 +1,10 @@
 extern crate secp256k1;
 extern crate sha3;
 
-use multiproof_rs::{ByteKey, Multiproof, NibbleKey};
+use std::collections::HashMap;
+
+use ethereum_types::U256;
+use multiproof_rs::{ByteKey, Multiproof, NibbleKey, Tree};
 use secp256k1::{
     recover as secp256k1_recover, sign as secp256k1_sign, verify as secp256k1_verify, Message,
     RecoveryId, SecretKey, Signature,
@@ -11,19 +14,21 @@ use sha3::{Digest, Keccak256};
 #[derive(Debug, PartialEq)]
 pub enum Account {
     // Address, nonce, value, code, state
-    Existing(NibbleKey, u64, u64, Vec<u8>, Vec<u8>),
+    Existing(NibbleKey, u64, U256, Vec<u8>, Vec<u8>),
+    // Address, nonce, balance commitment, code, state
+    Confidential(NibbleKey, u64, Commitment, Vec<u8>, Vec<u8>),
     Empty,
 }
 
 impl Account {
-    pub fn balance(&self) -> u64 {
+    pub fn balance(&self) -> U256 {
         match self {
             Self::Existing(_, _, balance, _, _) => *balance,
-            _ => 0u64,
+            _ => U256::zero(),
         }
     }
 
-    pub fn balance_mut(&mut self) -> Option<&mut u64> {
+    pub fn balance_mut(&mut self) -> Option<&mut U256> {
         match self {
             Self::Existing(_, _, ref mut balance, _, _) => Some(balance),
             _ => None,
@@ -32,43 +37,120 @@ impl Account {
 
     pub fn nonce(&self) -> u64 {
         match self {
-            Self::Existing(_, nonce, _, _, _) => *nonce,
+            Self::Existing(_, nonce, _, _, _) | Self::Confidential(_, nonce, _, _, _) => *nonce,
             _ => 0u64,
         }
     }
 
     pub fn nonce_mut(&mut self) -> Option<&mut u64> {
         match self {
-            Self::Existing(_, ref mut nonce, _, _, _) => Some(nonce),
+            Self::Existing(_, ref mut nonce, _, _, _)
+            | Self::Confidential(_, ref mut nonce, _, _, _) => Some(nonce),
+            _ => None,
+        }
+    }
+
+    /// Returns the balance commitment of a confidential account.
+    pub fn balance_commitment(&self) -> Option<&Commitment> {
+        match self {
+            Self::Confidential(_, _, commitment, _, _) => Some(commitment),
             _ => None,
         }
     }
 }
 
 impl Account {
-    pub fn deposit(&mut self, amount: u64) -> Result<(), &str> {
+    pub fn deposit(&mut self, amount: U256) -> Result<(), &str> {
         match self {
-            Account::Existing(_, _, ref mut balance, _, _) => *balance += amount,
+            Account::Existing(_, _, ref mut balance, _, _) => {
+                *balance = balance
+                    .checked_add(amount)
+                    .ok_or("balance overflow on deposit")?;
+            }
             _ => return Err("can not increase the balance of an empty account"),
         }
         Ok(())
     }
 
-    pub fn withdraw(&mut self, amount: u64) -> Result<(), &str> {
+    pub fn withdraw(&mut self, amount: U256) -> Result<(), &str> {
         match self {
             Account::Existing(_, _, ref mut balance, _, _) => {
-                if *balance >= amount {
-                    *balance += amount
-                } else {
-                    return Err("Insufficient balance");
-                }
+                *balance = balance
+                    .checked_sub(amount)
+                    .ok_or("Insufficient balance")?;
             }
-            _ => return Err("Can not increase the balance of an empty account"),
+            _ => return Err("Can not decrease the balance of an empty account"),
         }
         Ok(())
     }
 }
 
+/// Derives the storage trie key of a slot, i.e. `keccak256(slot)` laid out as
+/// a `NibbleKey`.
+fn storage_key(slot: &[u8]) -> NibbleKey {
+    let mut keccak256 = Keccak256::new();
+    keccak256.input(slot);
+    NibbleKey::from(ByteKey::from(keccak256.result().to_vec()))
+}
+
+impl Account {
+    /// Returns this account's storage root (the `state` field), or `None` for
+    /// an empty account.
+    pub fn storage_root(&self) -> Option<&Vec<u8>> {
+        match self {
+            Account::Existing(_, _, _, _, state) | Account::Confidential(_, _, _, _, state) => {
+                Some(state)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns a mutable handle to this account's storage root, so a
+    /// precompile can splice in the root returned by `storage_set`. `None`
+    /// for an empty account.
+    pub fn state_mut(&mut self) -> Option<&mut Vec<u8>> {
+        match self {
+            Account::Existing(_, _, _, _, ref mut state)
+            | Account::Confidential(_, _, _, _, ref mut state) => Some(state),
+            _ => None,
+        }
+    }
+
+    /// Reads the value stored at `slot`, proven by `storage_proof` against this
+    /// account's storage root. Returns `None` if the proof does not rebuild the
+    /// storage root or the slot is absent from it.
+    pub fn storage_get(&self, slot: &[u8], storage_proof: &Multiproof) -> Option<Vec<u8>> {
+        let root = self.storage_root()?;
+        let trie = storage_proof.rebuild().ok()?;
+        if &trie.hash() != root {
+            return None;
+        }
+        trie.get(&storage_key(slot)).cloned()
+    }
+
+    /// Writes `value` at `slot` in the storage trie proven by `storage_proof`
+    /// and returns the updated storage root, so the executor can thread it back
+    /// into the account leaf. Fails if the proof does not reconstruct the
+    /// account's current storage root.
+    pub fn storage_set(
+        &self,
+        slot: &[u8],
+        value: Vec<u8>,
+        storage_proof: &Multiproof,
+    ) -> Result<Vec<u8>, &'static str> {
+        let root = self.storage_root().ok_or("empty account has no storage")?;
+        let mut trie = storage_proof
+            .rebuild()
+            .map_err(|_| "invalid storage proof")?;
+        if &trie.hash() != root {
+            return Err("storage proof does not match storage root");
+        }
+        trie.insert(&storage_key(slot), value)
+            .map_err(|_| "could not write storage slot")?;
+        Ok(trie.hash())
+    }
+}
+
 impl From<SecretKey> for Account {
     fn from(sk: SecretKey) -> Self {
         let msg = Message::parse_slice(&[0x55u8; 32]).unwrap();
@@ -78,7 +160,7 @@ impl From<SecretKey> for Account {
         keccak256.input(&user1_pkey.serialize()[..]);
         let addr1 = keccak256.result_reset()[..20].to_vec();
         let user1_addr = NibbleKey::from(ByteKey::from(addr1));
-        Account::Existing(user1_addr, 0, 0, vec![], vec![])
+        Account::Existing(user1_addr, 0, U256::zero(), vec![], vec![])
     }
 }
 
@@ -97,8 +179,21 @@ impl rlp::Decodable for Account {
 
                 Ok(Account::Existing(addr, nonce, balance, code, state))
             }
+            6 => {
+                // Confidential accounts carry a trailing marker byte so they can
+                // be told apart from plaintext ones on the wire.
+                let addr = NibbleKey::from(rlp.val_at::<Vec<u8>>(0)?);
+                let nonce = rlp.val_at(1)?;
+                let commitment = rlp.val_at(2)?;
+                let code = rlp.val_at(3)?;
+                let state = rlp.val_at(4)?;
+                Ok(Account::Confidential(addr, nonce, commitment, code, state))
+            }
             0 => Ok(Account::Empty),
-            n => panic!(format!("Invalid payload, item count={}", n)),
+            // Reachable from proof data supplied by a possibly-adversarial
+            // block producer, so a malformed leaf must fail decoding rather
+            // than panic the process.
+            _ => Err(rlp::DecoderError::Custom("invalid account payload item count")),
         }
     }
 }
@@ -119,19 +214,469 @@ impl rlp::Encodable for Account {
                     .append(state)
                     .finalize_unbounded_list();
             }
+            Account::Confidential(addr, nonce, commitment, code, state) => {
+                stream
+                    .begin_unbounded_list()
+                    .append(addr)
+                    .append(nonce)
+                    .append(commitment)
+                    .append(code)
+                    .append(state)
+                    .append(&1u8) // marker distinguishing the 6-item confidential layout
+                    .finalize_unbounded_list();
+            }
         };
     }
 }
 
+// ---------------------------------------------------------------------------
+// Confidential values.
+//
+// A hidden amount is a Pedersen commitment `C = v·G + r·H` over the secp256k1
+// group, with `G` the standard generator and `H` a nothing-up-my-sleeve second
+// generator obtained by hashing a fixed label to the curve. Commitments add
+// homomorphically, so value conservation can be checked without ever learning
+// `v`, and a bit-decomposition range proof keeps `v` from wrapping negative.
+//
+// Confidential amounts are bounded to 64 bits, matching the plaintext `value`
+// width the crate used before balances were widened to `U256`.
+
+/// Number of bits a confidential value is decomposed into for its range proof.
+const RANGE_BITS: usize = 64;
+
+/// Domain-separation label hashed to the curve to obtain the second Pedersen
+/// generator `H`.
+const H_LABEL: &[u8] = b"jupiter-account/pedersen/generator-H";
+
+use secp256k1::curve::{Affine, Field, Jacobian, Scalar, ECMULT_CONTEXT, ECMULT_GEN_CONTEXT};
+
+/// Turns a `U256` into a curve scalar, reduced modulo the group order.
+fn scalar_from_u256(v: U256) -> Scalar {
+    let mut buf = [0u8; 32];
+    v.to_big_endian(&mut buf);
+    let mut s = Scalar::default();
+    // The overflow flag is intentionally ignored: values are reduced mod n.
+    let _ = s.set_b32(&buf);
+    s
+}
+
+/// Turns a small integer into a curve scalar.
+fn scalar_from_u64(v: u64) -> Scalar {
+    scalar_from_u256(U256::from(v))
+}
+
+/// Derives a scalar from arbitrary bytes via Keccak256, used both for the
+/// Fiat–Shamir challenge and for deterministic blinding/nonce derivation.
+fn scalar_from_hash(parts: &[&[u8]]) -> Scalar {
+    let mut keccak256 = Keccak256::new();
+    for p in parts {
+        keccak256.input(p);
+    }
+    let mut buf = [0u8; 32];
+    buf.copy_from_slice(&keccak256.result());
+    let mut s = Scalar::default();
+    let _ = s.set_b32(&buf);
+    s
+}
+
+/// The nothing-up-my-sleeve generator `H`, obtained by try-and-increment
+/// hashing of [`H_LABEL`] onto the curve.
+fn generator_h() -> Affine {
+    for counter in 0u32..=u32::max_value() {
+        let mut keccak256 = Keccak256::new();
+        keccak256.input(H_LABEL);
+        keccak256.input(&counter.to_be_bytes());
+        let mut xb = [0u8; 32];
+        xb.copy_from_slice(&keccak256.result());
+        let mut x = Field::default();
+        if !x.set_b32(&xb) {
+            continue;
+        }
+        let mut point = Affine::default();
+        if point.set_xo_var(&x, false) {
+            return point;
+        }
+    }
+    unreachable!("a valid x coordinate exists within the search range")
+}
+
+/// `scalar·base`, computed as a full multi-scalar multiplication with a zero
+/// `G` coefficient.
+fn point_mul(base: &Affine, scalar: &Scalar) -> Jacobian {
+    let mut base_j = Jacobian::default();
+    base_j.set_ge(base);
+    let mut out = Jacobian::default();
+    ECMULT_CONTEXT.ecmult(&mut out, &base_j, scalar, &Scalar::default());
+    out
+}
+
+/// `v·G + r·H`, the raw Pedersen commitment as a curve point.
+fn commit_point(v: &Scalar, r: &Scalar) -> Jacobian {
+    let h = generator_h();
+    let mut h_j = Jacobian::default();
+    h_j.set_ge(&h);
+    // r·H (via the general context) plus v·G (via the generator context).
+    let mut rh = Jacobian::default();
+    ECMULT_CONTEXT.ecmult(&mut rh, &h_j, r, &Scalar::default());
+    let mut vg = Jacobian::default();
+    ECMULT_GEN_CONTEXT.ecmult_gen(&mut vg, v);
+    let mut out = Jacobian::default();
+    out.add_var(&rh, &vg, None);
+    out
+}
+
+/// Serializes a curve point to its 33-byte compressed encoding.
+fn serialize_point(p: &Jacobian) -> [u8; 33] {
+    let mut a = Affine::from_gej(p);
+    a.x.normalize();
+    a.y.normalize();
+    let mut out = [0u8; 33];
+    out[0] = if a.y.is_odd() { 0x03 } else { 0x02 };
+    out[1..].copy_from_slice(&a.x.b32());
+    out
+}
+
+/// Parses a 33-byte compressed point into Jacobian form.
+fn parse_point(bytes: &[u8; 33]) -> Option<Jacobian> {
+    let mut x = Field::default();
+    let mut xb = [0u8; 32];
+    xb.copy_from_slice(&bytes[1..]);
+    if !x.set_b32(&xb) {
+        return None;
+    }
+    let mut a = Affine::default();
+    if !a.set_xo_var(&x, bytes[0] == 0x03) {
+        return None;
+    }
+    let mut j = Jacobian::default();
+    j.set_ge(&a);
+    Some(j)
+}
+
+/// A Pedersen commitment `C = v·G + r·H`, stored as a 33-byte compressed point.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Commitment(pub [u8; 33]);
+
+impl Commitment {
+    /// Commits to `value` under blinding factor `blinding`.
+    pub fn new(value: U256, blinding: &Scalar) -> Self {
+        Commitment(serialize_point(&commit_point(&scalar_from_u256(value), blinding)))
+    }
+
+    fn point(&self) -> Option<Jacobian> {
+        parse_point(&self.0)
+    }
+
+    /// Homomorphic addition `self + other`.
+    pub fn add(&self, other: &Commitment) -> Option<Commitment> {
+        let a = self.point()?;
+        let b = other.point()?;
+        let mut out = Jacobian::default();
+        out.add_var(&a, &b, None);
+        Some(Commitment(serialize_point(&out)))
+    }
+
+    /// Homomorphic subtraction `self - other`.
+    pub fn sub(&self, other: &Commitment) -> Option<Commitment> {
+        let a = self.point()?;
+        let b = other.point()?;
+        let mut out = Jacobian::default();
+        out.add_var(&a, &b.neg(), None);
+        Some(Commitment(serialize_point(&out)))
+    }
+}
+
+impl rlp::Encodable for Commitment {
+    fn rlp_append(&self, stream: &mut rlp::RlpStream) {
+        stream.append(&self.0.to_vec());
+    }
+}
+
+impl rlp::Decodable for Commitment {
+    fn decode(rlp: &rlp::Rlp) -> Result<Self, rlp::DecoderError> {
+        let bytes = rlp.as_val::<Vec<u8>>()?;
+        if bytes.len() != 33 {
+            return Err(rlp::DecoderError::Custom("commitment must be 33 bytes"));
+        }
+        let mut out = [0u8; 33];
+        out.copy_from_slice(&bytes);
+        Ok(Commitment(out))
+    }
+}
+
+/// Two-branch Schnorr OR-proof that a bit commitment `C = b·G + r·H` opens to
+/// `b = 0` or `b = 1`, over base `H` (the `b = 1` branch proves knowledge of
+/// `r` for `C - G`).
+#[derive(Clone, Debug, PartialEq)]
+struct BitProof {
+    commitment: [u8; 33],
+    nonce0: [u8; 33],
+    nonce1: [u8; 33],
+    chal0: [u8; 32],
+    chal1: [u8; 32],
+    resp0: [u8; 32],
+    resp1: [u8; 32],
+}
+
+/// Range proof for a confidential value: one OR-proof per decomposed bit plus
+/// the homomorphic consistency `C == Σ 2^i·C_i`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RangeProof {
+    bits: Vec<BitProof>,
+}
+
+fn scalar_bytes(s: &Scalar) -> [u8; 32] {
+    s.b32()
+}
+
+/// `p - G`, used to build the `b = 1` branch statement `C - G`.
+fn sub_generator(p: &Jacobian) -> Jacobian {
+    let mut g = Jacobian::default();
+    ECMULT_GEN_CONTEXT.ecmult_gen(&mut g, &scalar_from_u64(1));
+    let mut out = Jacobian::default();
+    out.add_var(p, &g.neg(), None);
+    out
+}
+
+impl RangeProof {
+    /// Proves that `value` lies in `[0, 2^RANGE_BITS)` under the commitment
+    /// blinded by `blinding`. Per-bit blindings are derived deterministically
+    /// from `blinding` so the bottom `RANGE_BITS - 1` are pseudo-random and the
+    /// top bit is fixed up to satisfy `Σ 2^i·r_i == r`.
+    pub fn prove(value: U256, blinding: &Scalar) -> Self {
+        let h = generator_h();
+
+        // Deterministic per-bit blindings, with the top one chosen so the
+        // weighted sum equals the overall blinding factor.
+        let mut blindings: Vec<Scalar> = (0..RANGE_BITS)
+            .map(|i| scalar_from_hash(&[&scalar_bytes(blinding), b"r", &(i as u32).to_be_bytes()]))
+            .collect();
+        let mut acc = Scalar::default();
+        for i in 0..RANGE_BITS - 1 {
+            acc += blindings[i] * scalar_from_u256(U256::one() << i);
+        }
+        let top_weight = scalar_from_u256(U256::one() << (RANGE_BITS - 1));
+        let inv = top_weight.inv();
+        blindings[RANGE_BITS - 1] = (*blinding + acc.neg()) * inv;
+
+        let mut bits = Vec::with_capacity(RANGE_BITS);
+        for i in 0..RANGE_BITS {
+            let bit = (value >> i) & U256::one() == U256::one();
+            let r_i = blindings[i];
+            let c_i = commit_point(&scalar_from_u64(if bit { 1 } else { 0 }), &r_i);
+
+            // The honest branch proves knowledge of r_i over base H; the other
+            // branch is simulated.
+            let k = scalar_from_hash(&[&scalar_bytes(blinding), b"k", &(i as u32).to_be_bytes()]);
+            let sim_chal =
+                scalar_from_hash(&[&scalar_bytes(blinding), b"e", &(i as u32).to_be_bytes()]);
+            let sim_resp =
+                scalar_from_hash(&[&scalar_bytes(blinding), b"s", &(i as u32).to_be_bytes()]);
+
+            // Statements: P0 = C_i (base H, b=0), P1 = C_i - G (base H, b=1).
+            let p0 = c_i;
+            let p1 = sub_generator(&c_i);
+
+            // Commitment of the honest branch: R = k·H.
+            let r_commit = point_mul(&h, &k);
+            // Simulated branch commitment: R' = s'·H - e'·P'.
+            let make_sim = |p: &Jacobian| {
+                let sh = point_mul(&h, &sim_resp);
+                let ep = {
+                    let mut out = Jacobian::default();
+                    ECMULT_CONTEXT.ecmult(&mut out, p, &sim_chal, &Scalar::default());
+                    out
+                };
+                let mut out = Jacobian::default();
+                out.add_var(&sh, &ep.neg(), None);
+                out
+            };
+
+            let (nonce0, nonce1) = if bit {
+                (make_sim(&p0), r_commit)
+            } else {
+                (r_commit, make_sim(&p1))
+            };
+
+            // Fiat–Shamir challenge over both branch commitments.
+            let e = scalar_from_hash(&[
+                &serialize_point(&nonce0),
+                &serialize_point(&nonce1),
+                &serialize_point(&c_i),
+            ]);
+            let honest_chal = e + sim_chal.neg();
+            let honest_resp = k + honest_chal * r_i;
+
+            let (chal0, chal1, resp0, resp1) = if bit {
+                (sim_chal, honest_chal, sim_resp, honest_resp)
+            } else {
+                (honest_chal, sim_chal, honest_resp, sim_resp)
+            };
+
+            bits.push(BitProof {
+                commitment: serialize_point(&c_i),
+                nonce0: serialize_point(&nonce0),
+                nonce1: serialize_point(&nonce1),
+                chal0: scalar_bytes(&chal0),
+                chal1: scalar_bytes(&chal1),
+                resp0: scalar_bytes(&resp0),
+                resp1: scalar_bytes(&resp1),
+            });
+        }
+
+        RangeProof { bits }
+    }
+
+    /// Verifies that every bit opens to 0 or 1 and that the bit commitments
+    /// recombine into `commitment`.
+    pub fn verify(&self, commitment: &Commitment) -> bool {
+        if self.bits.len() != RANGE_BITS {
+            return false;
+        }
+        let h = generator_h();
+
+        let mut recombined = Jacobian::default();
+        recombined.set_infinity();
+
+        for (i, bp) in self.bits.iter().enumerate() {
+            let c_i = match parse_point(&bp.commitment) {
+                Some(p) => p,
+                None => return false,
+            };
+            let (n0, n1) = match (parse_point(&bp.nonce0), parse_point(&bp.nonce1)) {
+                (Some(a), Some(b)) => (a, b),
+                _ => return false,
+            };
+            let mut e0 = Scalar::default();
+            let mut e1 = Scalar::default();
+            let mut s0 = Scalar::default();
+            let mut s1 = Scalar::default();
+            let _ = e0.set_b32(&bp.chal0);
+            let _ = e1.set_b32(&bp.chal1);
+            let _ = s0.set_b32(&bp.resp0);
+            let _ = s1.set_b32(&bp.resp1);
+
+            // Challenge must equal e0 + e1.
+            let e = scalar_from_hash(&[&bp.nonce0, &bp.nonce1, &bp.commitment]);
+            if scalar_bytes(&(e0 + e1)) != scalar_bytes(&e) {
+                return false;
+            }
+
+            // Branch 0: s0·H == R0 + e0·C_i.
+            let lhs0 = point_mul(&h, &s0);
+            let mut e0p = Jacobian::default();
+            ECMULT_CONTEXT.ecmult(&mut e0p, &c_i, &e0, &Scalar::default());
+            let mut rhs0 = Jacobian::default();
+            rhs0.add_var(&n0, &e0p, None);
+            if serialize_point(&lhs0) != serialize_point(&rhs0) {
+                return false;
+            }
+
+            // Branch 1: s1·H == R1 + e1·(C_i - G).
+            let p1 = sub_generator(&c_i);
+            let lhs1 = point_mul(&h, &s1);
+            let mut e1p = Jacobian::default();
+            ECMULT_CONTEXT.ecmult(&mut e1p, &p1, &e1, &Scalar::default());
+            let mut rhs1 = Jacobian::default();
+            rhs1.add_var(&n1, &e1p, None);
+            if serialize_point(&lhs1) != serialize_point(&rhs1) {
+                return false;
+            }
+
+            // Accumulate 2^i·C_i into the recombined commitment.
+            let mut weighted = Jacobian::default();
+            ECMULT_CONTEXT.ecmult(
+                &mut weighted,
+                &c_i,
+                &scalar_from_u256(U256::one() << i),
+                &Scalar::default(),
+            );
+            let mut acc = Jacobian::default();
+            acc.add_var(&recombined, &weighted, None);
+            recombined = acc;
+        }
+
+        match commitment.point() {
+            Some(c) => serialize_point(&recombined) == serialize_point(&c),
+            None => false,
+        }
+    }
+}
+
+impl rlp::Encodable for BitProof {
+    fn rlp_append(&self, stream: &mut rlp::RlpStream) {
+        stream
+            .begin_unbounded_list()
+            .append(&self.commitment.to_vec())
+            .append(&self.nonce0.to_vec())
+            .append(&self.nonce1.to_vec())
+            .append(&self.chal0.to_vec())
+            .append(&self.chal1.to_vec())
+            .append(&self.resp0.to_vec())
+            .append(&self.resp1.to_vec())
+            .finalize_unbounded_list();
+    }
+}
+
+fn fixed<const N: usize>(v: Vec<u8>) -> Result<[u8; N], rlp::DecoderError> {
+    if v.len() != N {
+        return Err(rlp::DecoderError::Custom("unexpected fixed-array length"));
+    }
+    let mut out = [0u8; N];
+    out.copy_from_slice(&v);
+    Ok(out)
+}
+
+impl rlp::Decodable for BitProof {
+    fn decode(rlp: &rlp::Rlp) -> Result<Self, rlp::DecoderError> {
+        Ok(BitProof {
+            commitment: fixed(rlp.val_at(0)?)?,
+            nonce0: fixed(rlp.val_at(1)?)?,
+            nonce1: fixed(rlp.val_at(2)?)?,
+            chal0: fixed(rlp.val_at(3)?)?,
+            chal1: fixed(rlp.val_at(4)?)?,
+            resp0: fixed(rlp.val_at(5)?)?,
+            resp1: fixed(rlp.val_at(6)?)?,
+        })
+    }
+}
+
+impl rlp::Encodable for RangeProof {
+    fn rlp_append(&self, stream: &mut rlp::RlpStream) {
+        stream.append_list(&self.bits);
+    }
+}
+
+impl rlp::Decodable for RangeProof {
+    fn decode(rlp: &rlp::Rlp) -> Result<Self, rlp::DecoderError> {
+        Ok(RangeProof {
+            bits: rlp.as_list()?,
+        })
+    }
+}
+
 /// Represents a layer-2 transaction.
 #[derive(Debug)]
 pub struct Tx {
     pub from: NibbleKey,
     pub to: NibbleKey,
     pub nonce: u64,
-    pub value: u64,
+    pub value: U256,
     pub call: u32, // Txs have only one instruction in this model, and it's a "call"
     pub data: Vec<u8>,
+    pub chain_id: u64, // EIP-155 replay protection, bound into the signature
+    /// When set, `value` is hidden behind `value_commitment` and validated by
+    /// `range_proof` instead of being transferred in the clear.
+    pub confidential: bool,
+    pub value_commitment: Option<Commitment>,
+    pub range_proof: Option<RangeProof>,
+    /// Range proof over the sender's *post-transfer* balance commitment
+    /// (`balance - value`, derivable by anyone from the sender's current
+    /// balance commitment and `value_commitment`). Required for a confidential
+    /// tx to be accepted by `TxData::apply`: only a sender whose real balance
+    /// covers `value` can produce one, since the commitment it proves is
+    /// fixed by the chain, not chosen by the prover.
+    pub balance_range_proof: Option<RangeProof>,
     pub signature: Vec<u8>,
 }
 
@@ -145,13 +690,55 @@ impl rlp::Encodable for Tx {
             .append(&self.value)
             .append(&self.call)
             .append(&self.data)
+            .append(&self.chain_id)
             .append(&self.signature)
-            .finalize_unbounded_list();
+            .append(&(self.confidential as u8));
+        // The confidential payload is only present when the flag is set.
+        match (
+            &self.value_commitment,
+            &self.range_proof,
+            &self.balance_range_proof,
+        ) {
+            (Some(c), Some(p), Some(bp)) => {
+                stream.append(c).append(p).append(bp);
+            }
+            _ => {
+                stream
+                    .append_empty_data()
+                    .append_empty_data()
+                    .append_empty_data();
+            }
+        }
+        stream.finalize_unbounded_list();
     }
 }
 
 impl rlp::Decodable for Tx {
     fn decode(rlp: &rlp::Rlp) -> Result<Self, rlp::DecoderError> {
+        // The encoding grew over time: 7 items is the original layout, 8 adds
+        // `chain_id`, and 12 adds the confidential payload. Missing trailing
+        // fields decode to their neutral defaults.
+        let count = rlp.item_count()?;
+        let (chain_id, signature) = if count >= 8 {
+            (rlp.val_at(6)?, rlp.val_at(7)?)
+        } else {
+            (0u64, rlp.val_at(6)?)
+        };
+        let (confidential, value_commitment, range_proof, balance_range_proof) = if count >= 12 {
+            let confidential = rlp.val_at::<u8>(8)? != 0;
+            if confidential {
+                (
+                    true,
+                    Some(rlp.val_at(9)?),
+                    Some(rlp.val_at(10)?),
+                    Some(rlp.val_at(11)?),
+                )
+            } else {
+                (false, None, None, None)
+            }
+        } else {
+            (false, None, None, None)
+        };
         Ok(Tx {
             from: NibbleKey::from(rlp.val_at::<Vec<u8>>(0)?),
             to: NibbleKey::from(rlp.val_at::<Vec<u8>>(1)?),
@@ -159,7 +746,12 @@ impl rlp::Decodable for Tx {
             value: rlp.val_at(3)?,
             call: rlp.val_at(4)?,
             data: rlp.val_at(5)?,
-            signature: rlp.val_at(6)?,
+            chain_id,
+            confidential,
+            value_commitment,
+            range_proof,
+            balance_range_proof,
+            signature,
         })
     }
 }
@@ -172,28 +764,75 @@ impl Tx {
             nonce: nonce,
             signature: vec![0u8; 65],
             call: 0,
-            value: 0,
+            value: U256::zero(),
             data: vec![],
+            chain_id: 0,
+            confidential: false,
+            value_commitment: None,
+            range_proof: None,
+            balance_range_proof: None,
         }
     }
-    pub fn sign(&mut self, skey: &[u8; 32]) {
-        let skey = SecretKey::parse(skey).unwrap();
-        let mut keccak256 = Keccak256::new();
-        keccak256.input(rlp::encode(&self.from));
-        keccak256.input(rlp::encode(&self.to));
-        keccak256.input(rlp::encode(&self.nonce));
-        keccak256.input(rlp::encode(&self.value));
-        keccak256.input(rlp::encode(&self.call));
-        keccak256.input(rlp::encode(&self.data));
-        let message_data = keccak256.result();
-        let message = Message::parse_slice(&message_data).unwrap();
-        let (sig, recid) = secp256k1_sign(&message, &skey);
-        self.signature[..64].copy_from_slice(&sig.serialize()[..]);
-        self.signature[64] = recid.serialize();
+
+    /// Turns this into a confidential transfer of `value` hidden behind a
+    /// Pedersen commitment blinded by `blinding`, attaching the bit-decomposition
+    /// range proof that keeps `value` in `[0, 2^RANGE_BITS)`.
+    ///
+    /// Also attaches a range proof over the sender's resulting balance
+    /// commitment, `sender_balance - value` blinded by `sender_blinding -
+    /// blinding`. That post-transfer commitment is public — the executor
+    /// derives the same point homomorphically from the sender's current
+    /// balance commitment and `value_commitment` — so this is what actually
+    /// stops a confidential transfer from exceeding the sender's real
+    /// balance: a sender who doesn't hold `value` cannot range-prove the
+    /// result. Fails without attaching anything if `value` exceeds
+    /// `sender_balance`.
+    pub fn prove_range(
+        &mut self,
+        value: U256,
+        blinding: &Scalar,
+        sender_balance: U256,
+        sender_blinding: &Scalar,
+    ) -> Result<(), &'static str> {
+        let new_balance = sender_balance
+            .checked_sub(value)
+            .ok_or("insufficient confidential balance")?;
+        let new_blinding = *sender_blinding + blinding.neg();
+
+        self.confidential = true;
+        self.value = U256::zero();
+        self.value_commitment = Some(Commitment::new(value, blinding));
+        self.range_proof = Some(RangeProof::prove(value, blinding));
+        self.balance_range_proof = Some(RangeProof::prove(new_balance, &new_blinding));
+        Ok(())
+    }
+
+    /// Checks the attached range proof against the value commitment. Returns
+    /// `false` if either is missing or the proof does not verify.
+    pub fn verify_range(&self) -> bool {
+        match (&self.value_commitment, &self.range_proof) {
+            (Some(commitment), Some(proof)) => proof.verify(commitment),
+            _ => false,
+        }
     }
 
-    pub fn sig_check(&self) -> (bool, NibbleKey) {
-        // Recover the signature from the tx data.
+    /// Checks the attached balance range proof against `from_new`, the
+    /// publicly-derived post-transfer sender balance commitment. Returns
+    /// `false` if the proof is missing or does not verify.
+    pub fn verify_balance_range(&self, from_new: &Commitment) -> bool {
+        match &self.balance_range_proof {
+            Some(proof) => proof.verify(from_new),
+            None => false,
+        }
+    }
+    /// Computes the Keccak256 signing digest over the tx fields.
+    ///
+    /// This is also the tx's identity for the purposes of batch signature
+    /// verification: two txs only ever share a digest if every one of these
+    /// fields (including `to`, `value`, `call`, `data`, `chain_id` and the
+    /// confidential commitment) matches, unlike the weaker `(from, nonce)`
+    /// pair.
+    fn signing_digest(&self) -> [u8; 32] {
         let mut keccak256 = Keccak256::new();
         keccak256.input(rlp::encode(&self.from));
         keccak256.input(rlp::encode(&self.to));
@@ -201,31 +840,523 @@ impl Tx {
         keccak256.input(rlp::encode(&self.value));
         keccak256.input(rlp::encode(&self.call));
         keccak256.input(rlp::encode(&self.data));
-        let message_data = keccak256.result_reset();
-        let message = Message::parse_slice(&message_data).unwrap();
-        let signature = Signature::parse_slice(&self.signature[..64]).unwrap();
-        let recover = RecoveryId::parse(self.signature[64]).unwrap();
-        let pkey = secp256k1_recover(&message, &signature, &recover).unwrap();
-
-        // Verify the signature
-        if !secp256k1_verify(&message, &signature, &pkey) {
-            return (false, NibbleKey::from(vec![]));
+        keccak256.input(rlp::encode(&self.chain_id));
+        // A confidential tx commits to its value commitment rather than the
+        // (zeroed) plaintext value, so the hidden amount cannot be swapped.
+        if let Some(commitment) = &self.value_commitment {
+            keccak256.input(&commitment.0);
         }
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&keccak256.result());
+        digest
+    }
+
+    /// The signing digest, parsed as a secp256k1 `Message`.
+    fn signing_message(&self) -> Message {
+        Message::parse_slice(&self.signing_digest()).unwrap()
+    }
 
-        // Get the address
+    /// Recovers the signer address from a precomputed signing digest and
+    /// returns it only if it matches `from` and the signature verifies.
+    fn recover_sender(&self, message: &Message) -> Option<NibbleKey> {
+        let signature = Signature::parse_slice(&self.signature[..64]).ok()?;
+        let recover = RecoveryId::parse(self.signature[64]).ok()?;
+        let pkey = secp256k1_recover(message, &signature, &recover).ok()?;
+        if !secp256k1_verify(message, &signature, &pkey) {
+            return None;
+        }
+        let mut keccak256 = Keccak256::new();
         keccak256.input(&pkey.serialize()[..]);
         let addr = keccak256.result()[..20].to_vec();
         let addr = NibbleKey::from(ByteKey::from(addr));
+        if addr == self.from {
+            Some(addr)
+        } else {
+            None
+        }
+    }
 
-        return (addr.clone() == self.from, addr);
+    pub fn sign(&mut self, skey: &[u8; 32]) {
+        let skey = SecretKey::parse(skey).unwrap();
+        let (sig, recid) = secp256k1_sign(&self.signing_message(), &skey);
+        self.signature[..64].copy_from_slice(&sig.serialize()[..]);
+        self.signature[64] = recid.serialize();
+    }
+
+    pub fn sig_check(&self, expected_chain_id: u64) -> (bool, NibbleKey) {
+        // Reject a tx that was signed for a different chain before spending any
+        // effort on the ECDSA recovery.
+        if self.chain_id != expected_chain_id {
+            return (false, NibbleKey::from(vec![]));
+        }
+
+        // A confidential tx is only valid if its range proof checks out.
+        if self.confidential && !self.verify_range() {
+            return (false, NibbleKey::from(vec![]));
+        }
+
+        match self.recover_sender(&self.signing_message()) {
+            Some(addr) => (true, addr),
+            None => (false, NibbleKey::from(vec![])),
+        }
     }
 }
 
+/// Error returned by the stateless executor, identifying the first tx that
+/// could not be applied against the proven pre-state.
+#[derive(Debug, PartialEq)]
+pub enum ExecError {
+    /// The multiproof could not be rebuilt into a trie, or a proven leaf could
+    /// not be decoded or written back into it.
+    Proof(String),
+    /// The proof does not reconstruct the advertised pre-state root.
+    RootMismatch,
+    /// The signature of the tx at the given index did not recover `from`, or
+    /// the tx was signed for a different chain.
+    InvalidSignature(usize),
+    /// `tx.nonce` did not match the sender account nonce.
+    BadNonce(usize),
+    /// The sender could not cover `tx.value`, or crediting the recipient
+    /// failed.
+    InsufficientBalance(usize),
+    /// The sender account is not part of the proof.
+    MissingSender(usize),
+    /// The call selector of the tx at the given index failed or is unknown.
+    CallFailed(usize),
+    /// A confidential tx carried an absent or invalid range proof.
+    RangeProof(usize),
+    /// A confidential transfer did not conserve value homomorphically, or a
+    /// confidential tx touched a non-confidential account (or vice versa).
+    Conservation(usize),
+}
+
+/// Derives the state trie key of an account from its address, i.e.
+/// `keccak256(address)` laid out as a `NibbleKey`.
+fn state_key(addr: &NibbleKey) -> NibbleKey {
+    let addr_bytes = ByteKey::from(addr.clone()).0;
+    let mut keccak256 = Keccak256::new();
+    keccak256.input(&addr_bytes);
+    NibbleKey::from(ByteKey::from(keccak256.result().to_vec()))
+}
+
+/// Error returned by a precompile when it cannot carry out its transition.
+#[derive(Debug, PartialEq)]
+pub enum CallError {
+    /// The selector had no registered handler.
+    UnknownSelector(u32),
+    /// The handler rejected its input.
+    Reverted(String),
+}
+
+/// A transition function invoked by the `call` selector, after the value has
+/// been transferred to the callee. Downstream crates register their own to
+/// extend the tx format without forking it.
+///
+/// `storage_proof` is the batch's storage proof (see
+/// [`TxData::storage_proof`]), passed unchanged to every tx; a precompile
+/// that reads or writes contract storage does so through `callee.storage_get`
+/// / `callee.storage_set` against it, then splices the returned root back
+/// into `callee` via `Account::state_mut`.
+pub trait Precompile {
+    fn execute(
+        &self,
+        caller: &NibbleKey,
+        callee: &mut Account,
+        data: &[u8],
+        value: U256,
+        storage_proof: &Multiproof,
+    ) -> Result<Vec<u8>, CallError>;
+}
+
+/// Selector `0`: the historical behavior, a no-op that leaves the callee
+/// untouched and returns its input unchanged.
+pub struct Identity;
+
+impl Precompile for Identity {
+    fn execute(
+        &self,
+        _caller: &NibbleKey,
+        _callee: &mut Account,
+        data: &[u8],
+        _value: U256,
+        _storage_proof: &Multiproof,
+    ) -> Result<Vec<u8>, CallError> {
+        Ok(data.to_vec())
+    }
+}
+
+/// Selector `1`: sets the callee's `code` field from `data`, the minimal
+/// "deploy" transition.
+pub struct DeployCode;
+
+impl Precompile for DeployCode {
+    fn execute(
+        &self,
+        _caller: &NibbleKey,
+        callee: &mut Account,
+        data: &[u8],
+        _value: U256,
+        _storage_proof: &Multiproof,
+    ) -> Result<Vec<u8>, CallError> {
+        match callee {
+            Account::Existing(_, _, _, ref mut code, _) => {
+                *code = data.to_vec();
+                Ok(vec![])
+            }
+            _ => Err(CallError::Reverted(
+                "cannot deploy code to an empty account".to_string(),
+            )),
+        }
+    }
+}
+
+/// Selector `2`: writes `data[32..]` at storage slot `data[..32]`, proven
+/// against the callee's current storage root via `storage_proof`, and returns
+/// the slot's previous value (empty if it was unset). The first precompile to
+/// actually exercise `Account::storage_get`/`storage_set`.
+pub struct SetStorage;
+
+impl Precompile for SetStorage {
+    fn execute(
+        &self,
+        _caller: &NibbleKey,
+        callee: &mut Account,
+        data: &[u8],
+        _value: U256,
+        storage_proof: &Multiproof,
+    ) -> Result<Vec<u8>, CallError> {
+        if data.len() < 32 {
+            return Err(CallError::Reverted(
+                "missing storage slot in call data".to_string(),
+            ));
+        }
+        let (slot, value) = data.split_at(32);
+
+        let previous = callee.storage_get(slot, storage_proof).unwrap_or_default();
+        let new_root = callee
+            .storage_set(slot, value.to_vec(), storage_proof)
+            .map_err(|e| CallError::Reverted(e.to_string()))?;
+        *callee
+            .state_mut()
+            .ok_or_else(|| CallError::Reverted("callee has no storage".to_string()))? = new_root;
+
+        Ok(previous)
+    }
+}
+
+/// The dispatch table shipped with the crate: identity at `0`, deploy at `1`,
+/// storage write at `2`.
+fn default_precompiles() -> HashMap<u32, Box<dyn Precompile>> {
+    let mut table: HashMap<u32, Box<dyn Precompile>> = HashMap::new();
+    table.insert(0, Box::new(Identity));
+    table.insert(1, Box::new(DeployCode));
+    table.insert(2, Box::new(SetStorage));
+    table
+}
+
 /// Represents the data that should be encoded inside a layer one `data` field.
-#[derive(Debug)]
 pub struct TxData {
     pub proof: Multiproof,
+    /// Proof of the touched storage slots, keyed by `keccak256(slot)`. It
+    /// rebuilds a single storage trie, so `storage_get`/`storage_set` accept
+    /// it only for an account whose storage root is that trie's root; slots
+    /// of accounts with other storage roots are not covered.
+    pub storage_proof: Multiproof,
     pub txs: Vec<Tx>,
+    /// Selector-indexed transition functions invoked after value transfer.
+    /// Not part of the wire encoding: rebuilt from the defaults on decode and
+    /// extended through `register_precompile`.
+    pub precompiles: HashMap<u32, Box<dyn Precompile>>,
+}
+
+impl std::fmt::Debug for TxData {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TxData")
+            .field("proof", &self.proof)
+            .field("storage_proof", &self.storage_proof)
+            .field("txs", &self.txs)
+            .field(
+                "precompiles",
+                &self.precompiles.keys().collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+/// Verifies every tx in `txs` against `expected_chain_id`, returning the
+/// recovered sender addresses in order, or the index of the first tx whose
+/// signature does not recover its `from`.
+///
+/// Message digests are computed up front and identical digests — not merely
+/// identical `(from, nonce)` pairs, which can collide across otherwise
+/// distinct txs carrying a forged signature — are only recovered once; the
+/// unique digests are then verified in parallel with rayon, so a block
+/// carrying hundreds of transactions does not pay the serial ECDSA cost.
+/// Free-standing (rather than a `TxData` method) so it can be exercised
+/// without a `Multiproof` fixture.
+fn verify_tx_signatures(txs: &[Tx], expected_chain_id: u64) -> Result<Vec<NibbleKey>, usize> {
+    use rayon::prelude::*;
+
+    // Precompute the signing digests for every tx.
+    let digests: Vec<[u8; 32]> = txs.iter().map(|tx| tx.signing_digest()).collect();
+
+    // Map each tx onto a representative index, so txs with an identical
+    // signing digest share a single recover+verify.
+    let mut representatives: HashMap<[u8; 32], usize> = HashMap::new();
+    let reps: Vec<usize> = digests
+        .iter()
+        .enumerate()
+        .map(|(i, digest)| *representatives.entry(*digest).or_insert(i))
+        .collect();
+
+    // Verify the unique representatives in parallel. A tx signed for the
+    // wrong chain is rejected here, before spending any effort recovering a
+    // signature that would otherwise validly recover `from`.
+    let mut unique: Vec<usize> = representatives.values().cloned().collect();
+    unique.sort_unstable();
+    let recovered: HashMap<usize, Option<NibbleKey>> = unique
+        .par_iter()
+        .map(|&i| {
+            let tx = &txs[i];
+            let addr = if tx.chain_id != expected_chain_id {
+                None
+            } else {
+                let message = Message::parse_slice(&digests[i]).unwrap();
+                tx.recover_sender(&message)
+            };
+            (i, addr)
+        })
+        .collect();
+
+    // Reassemble the per-tx answers in order, failing on the first invalid
+    // signature.
+    let mut senders = Vec::with_capacity(txs.len());
+    for (i, &rep) in reps.iter().enumerate() {
+        match &recovered[&rep] {
+            Some(addr) => senders.push(addr.clone()),
+            None => return Err(i),
+        }
+    }
+    Ok(senders)
+}
+
+impl TxData {
+    /// Registers a transition function under `id`, replacing any handler that
+    /// was previously bound to that selector.
+    pub fn register_precompile(&mut self, id: u32, precompile: Box<dyn Precompile>) {
+        self.precompiles.insert(id, precompile);
+    }
+
+    /// Verifies every transaction signature in the batch against
+    /// `expected_chain_id`, returning the recovered sender addresses in tx
+    /// order, or the index of the first transaction whose signature does not
+    /// recover its `from` (including one signed for a different chain). See
+    /// [`verify_tx_signatures`] for how duplicate work is elided.
+    pub fn verify_signatures(&self, expected_chain_id: u64) -> Result<Vec<NibbleKey>, usize> {
+        verify_tx_signatures(&self.txs, expected_chain_id)
+    }
+
+    /// Performs a stateless state transition, the way a light client would
+    /// verify a batch of account updates: the `Multiproof` is rebuilt and
+    /// checked against `pre_state_root`, every `Tx` is applied to the proven
+    /// account leaves, and the resulting post-state root is returned.
+    ///
+    /// `expected_chain_id` is the chain this executor is running for; a tx
+    /// signed for any other chain is rejected by `verify_signatures` before
+    /// it can touch any account (EIP-155 replay protection).
+    pub fn apply(&self, pre_state_root: &[u8], expected_chain_id: u64) -> Result<Vec<u8>, ExecError> {
+        // Rebuild the trie from the proof and make sure it reconstructs exactly
+        // the pre-state root the caller expects.
+        let mut trie = self.proof.rebuild().map_err(ExecError::Proof)?;
+        if trie.hash() != pre_state_root {
+            return Err(ExecError::RootMismatch);
+        }
+
+        // Verify the whole batch up front, routing through the parallel path.
+        let senders = self
+            .verify_signatures(expected_chain_id)
+            .map_err(ExecError::InvalidSignature)?;
+
+        for (i, tx) in self.txs.iter().enumerate() {
+            let from = &senders[i];
+
+            let from_key = state_key(from);
+            let to_key = state_key(&tx.to);
+
+            // Load the sender leaf; it must be present in the proof.
+            let mut sender = match trie.get(&from_key) {
+                Some(bytes) => rlp::decode::<Account>(bytes).map_err(|_| ExecError::Proof(
+                    "could not decode sender leaf".to_string(),
+                ))?,
+                None => return Err(ExecError::MissingSender(i)),
+            };
+
+            if tx.nonce != sender.nonce() {
+                return Err(ExecError::BadNonce(i));
+            }
+
+            if tx.confidential {
+                // Hidden-value path: the amount never appears in the clear, so
+                // conservation is checked by point arithmetic on commitments.
+                if !tx.verify_range() {
+                    return Err(ExecError::RangeProof(i));
+                }
+                let c_value = tx.value_commitment.as_ref().ok_or(ExecError::RangeProof(i))?;
+
+                // `from_key == to_key` means sender and recipient are the same
+                // leaf; resolve the debit and credit against one shared
+                // `Account` instead of decoding a second, stale copy that
+                // would otherwise clobber the debited leaf when both inserts
+                // land on the same key.
+                let same_account = from_key == to_key;
+                let recipient_absent = !same_account && trie.get(&to_key).is_none();
+
+                // The recipient is created with a zero-value commitment if
+                // absent, unless this is a self-transfer.
+                let mut recipient = if same_account {
+                    None
+                } else {
+                    Some(match trie.get(&to_key) {
+                        Some(bytes) => rlp::decode::<Account>(bytes).map_err(|_| {
+                            ExecError::Proof("could not decode recipient leaf".to_string())
+                        })?,
+                        None => Account::Confidential(
+                            tx.to.clone(),
+                            0,
+                            Commitment::new(U256::zero(), &scalar_from_u64(0)),
+                            vec![],
+                            vec![],
+                        ),
+                    })
+                };
+
+                let from_old = sender
+                    .balance_commitment()
+                    .ok_or(ExecError::Conservation(i))?
+                    .clone();
+                let to_old = if same_account {
+                    from_old.clone()
+                } else {
+                    recipient
+                        .as_ref()
+                        .unwrap()
+                        .balance_commitment()
+                        .ok_or(ExecError::Conservation(i))?
+                        .clone()
+                };
+
+                // C_from_new = C_from_old - C_value, C_to_new = C_to_old + C_value.
+                let from_new = from_old.sub(c_value).ok_or(ExecError::Conservation(i))?;
+                // A fresh recipient's zero commitment is the point at infinity,
+                // whose encoding cannot be added to, so its new commitment is
+                // `C_value` itself.
+                let to_new = if recipient_absent {
+                    c_value.clone()
+                } else {
+                    to_old.add(c_value).ok_or(ExecError::Conservation(i))?
+                };
+
+                // The sender's resulting balance commitment is public (just
+                // derived above); only a sender whose real balance covers
+                // `c_value` can have produced a range proof for it, so this
+                // is what actually rejects an overspend (there is no plaintext
+                // `balance < value` check to fall back on here).
+                if !tx.verify_balance_range(&from_new) {
+                    return Err(ExecError::RangeProof(i));
+                }
+
+                // A self-transfer (`from_key == to_key`) is one leaf, not two:
+                // its final commitment must reflect both the debit and the
+                // credit, not just whichever insert happens to land last.
+                let final_sender_commitment = if same_account {
+                    from_new.add(c_value).ok_or(ExecError::Conservation(i))?
+                } else {
+                    from_new
+                };
+
+                if let Account::Confidential(_, ref mut nonce, ref mut commitment, _, _) = sender {
+                    *nonce += 1;
+                    *commitment = final_sender_commitment;
+                }
+                trie.insert(&from_key, rlp::encode(&sender).to_vec())
+                    .map_err(ExecError::Proof)?;
+
+                if let Some(mut recipient) = recipient {
+                    if let Account::Confidential(_, _, ref mut commitment, _, _) = recipient {
+                        *commitment = to_new;
+                    }
+                    trie.insert(&to_key, rlp::encode(&recipient).to_vec())
+                        .map_err(ExecError::Proof)?;
+                }
+                continue;
+            }
+
+            // A plaintext tx must not touch a confidential account.
+            if sender.balance_commitment().is_some() {
+                return Err(ExecError::Conservation(i));
+            }
+
+            if sender.balance() < tx.value {
+                return Err(ExecError::InsufficientBalance(i));
+            }
+
+            // `from_key == to_key` means sender and recipient are the same
+            // leaf; debit first and credit that very same `Account` so the
+            // two effects land in one leaf instead of the credit being
+            // computed against a stale pre-debit copy that would clobber it
+            // when both inserts hit the same key.
+            let same_account = from_key == to_key;
+
+            sender.withdraw(tx.value).map_err(|_| ExecError::InsufficientBalance(i))?;
+            if let Some(nonce) = sender.nonce_mut() {
+                *nonce += 1;
+            }
+
+            // The recipient is created as a zero-balance `Account::Existing`
+            // if it is absent, unless this is a self-transfer.
+            let mut recipient = if same_account {
+                None
+            } else {
+                Some(match trie.get(&to_key) {
+                    Some(bytes) => rlp::decode::<Account>(bytes).map_err(|_| {
+                        ExecError::Proof("could not decode recipient leaf".to_string())
+                    })?,
+                    None => Account::Existing(tx.to.clone(), 0, U256::zero(), vec![], vec![]),
+                })
+            };
+            if recipient
+                .as_ref()
+                .map_or(false, |r| r.balance_commitment().is_some())
+            {
+                return Err(ExecError::Conservation(i));
+            }
+            let callee = recipient.as_mut().unwrap_or_else(|| &mut sender);
+            callee
+                .deposit(tx.value)
+                .map_err(|_| ExecError::InsufficientBalance(i))?;
+
+            // Run the call selector against the (now credited) recipient,
+            // giving it access to the batch's storage proof and the actual
+            // caller address (not its state-trie key).
+            let call_result: Result<Vec<u8>, CallError> = match self.precompiles.get(&tx.call) {
+                Some(precompile) => {
+                    precompile.execute(from, callee, &tx.data, tx.value, &self.storage_proof)
+                }
+                None => Err(CallError::UnknownSelector(tx.call)),
+            };
+            call_result.map_err(|_| ExecError::CallFailed(i))?;
+
+            // Splice the mutated leaf(ves) back into the trie.
+            trie.insert(&from_key, rlp::encode(&sender).to_vec())
+                .map_err(ExecError::Proof)?;
+            if let Some(recipient) = recipient {
+                trie.insert(&to_key, rlp::encode(&recipient).to_vec())
+                    .map_err(ExecError::Proof)?;
+            }
+        }
+
+        Ok(trie.hash())
+    }
 }
 
 impl rlp::Encodable for TxData {
@@ -233,6 +1364,7 @@ impl rlp::Encodable for TxData {
         stream
             .begin_unbounded_list()
             .append(&self.proof)
+            .append(&self.storage_proof)
             .append_list(&self.txs)
             .finalize_unbounded_list();
     }
@@ -242,7 +1374,9 @@ impl rlp::Decodable for TxData {
     fn decode(rlp: &rlp::Rlp) -> Result<Self, rlp::DecoderError> {
         Ok(TxData {
             proof: rlp.val_at::<Multiproof>(0)?,
-            txs: rlp.list_at(1)?,
+            storage_proof: rlp.val_at::<Multiproof>(1)?,
+            txs: rlp.list_at(2)?,
+            precompiles: default_precompiles(),
         })
     }
 }
@@ -251,6 +1385,185 @@ impl rlp::Decodable for TxData {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_account_decode_rejects_bad_item_count_instead_of_panicking() {
+        let mut stream = rlp::RlpStream::new();
+        stream
+            .begin_unbounded_list()
+            .append(&1u8)
+            .append(&2u8)
+            .append(&3u8)
+            .finalize_unbounded_list();
+        let bytes = stream.out();
+
+        assert!(rlp::decode::<Account>(&bytes).is_err());
+    }
+
+    /// Builds a full trie over `entries` and a `Multiproof` covering exactly
+    /// `proof_keys` against it, returning `(pre_state_root, proof)` so a test
+    /// can feed it straight into `TxData::apply`.
+    fn build_proof(
+        entries: &[(NibbleKey, Vec<u8>)],
+        proof_keys: &[NibbleKey],
+    ) -> (Vec<u8>, Multiproof) {
+        let mut trie = multiproof_rs::Node::default();
+        for (key, value) in entries {
+            trie.insert(key, value.clone()).unwrap();
+        }
+        let root = trie.hash();
+        let proof = trie.get_multiproof(proof_keys).unwrap();
+        (root, proof)
+    }
+
+    #[test]
+    fn test_apply_transfers_value_and_bumps_nonce() {
+        let from_bytes = vec![
+            181, 154, 35, 232, 170, 166, 228, 13, 59, 214, 229, 236, 205, 9, 152, 122, 184, 20,
+            30, 197,
+        ];
+        let to_bytes = vec![6u8; 20];
+
+        let from_addr = NibbleKey::from(ByteKey::from(from_bytes.clone()));
+        let to_addr = NibbleKey::from(ByteKey::from(to_bytes.clone()));
+        let from_key = state_key(&from_addr);
+        let to_key = state_key(&to_addr);
+
+        let sender_account =
+            Account::Existing(from_addr.clone(), 1, U256::from(100u64), vec![], vec![]);
+        let recipient_account =
+            Account::Existing(to_addr.clone(), 0, U256::from(5u64), vec![], vec![]);
+        let entries = vec![
+            (from_key.clone(), rlp::encode(&sender_account).to_vec()),
+            (to_key.clone(), rlp::encode(&recipient_account).to_vec()),
+        ];
+        let (root, proof) = build_proof(&entries, &[from_key.clone(), to_key.clone()]);
+
+        // After the tx the sender holds 70 at nonce 2 and the recipient 35.
+        let sender_after = Account::Existing(from_addr, 2, U256::from(70u64), vec![], vec![]);
+        let recipient_after = Account::Existing(to_addr, 0, U256::from(35u64), vec![], vec![]);
+        let mut expected = multiproof_rs::Node::default();
+        expected
+            .insert(&from_key, rlp::encode(&sender_after).to_vec())
+            .unwrap();
+        expected
+            .insert(&to_key, rlp::encode(&recipient_after).to_vec())
+            .unwrap();
+        let (_, storage_proof) = build_proof(&[], &[]);
+
+        let mut tx = Tx::new(from_bytes, to_bytes, 1);
+        tx.value = U256::from(30u64);
+        tx.sign(&[1u8; 32]);
+
+        let txdata = TxData {
+            proof,
+            storage_proof,
+            txs: vec![tx],
+            precompiles: default_precompiles(),
+        };
+
+        assert_eq!(txdata.apply(&root, 0), Ok(expected.hash()));
+    }
+
+    #[test]
+    fn test_apply_rejects_bad_nonce() {
+        let from_bytes = vec![
+            181, 154, 35, 232, 170, 166, 228, 13, 59, 214, 229, 236, 205, 9, 152, 122, 184, 20,
+            30, 197,
+        ];
+        let to_bytes = vec![6u8; 20];
+
+        let from_addr = NibbleKey::from(ByteKey::from(from_bytes.clone()));
+        let to_addr = NibbleKey::from(ByteKey::from(to_bytes.clone()));
+        let from_key = state_key(&from_addr);
+        let to_key = state_key(&to_addr);
+
+        // The leaf's nonce is 1, but the tx below is signed with nonce 0.
+        let sender_account = Account::Existing(from_addr, 1, U256::from(100u64), vec![], vec![]);
+        let entries = vec![(from_key.clone(), rlp::encode(&sender_account).to_vec())];
+        let (root, proof) = build_proof(&entries, &[from_key, to_key]);
+        let (_, storage_proof) = build_proof(&[], &[]);
+
+        let mut tx = Tx::new(from_bytes, to_bytes, 0);
+        tx.value = U256::from(30u64);
+        tx.sign(&[1u8; 32]);
+
+        let txdata = TxData {
+            proof,
+            storage_proof,
+            txs: vec![tx],
+            precompiles: default_precompiles(),
+        };
+
+        assert_eq!(txdata.apply(&root, 0), Err(ExecError::BadNonce(0)));
+    }
+
+    #[test]
+    fn test_apply_rejects_insufficient_balance() {
+        let from_bytes = vec![
+            181, 154, 35, 232, 170, 166, 228, 13, 59, 214, 229, 236, 205, 9, 152, 122, 184, 20,
+            30, 197,
+        ];
+        let to_bytes = vec![6u8; 20];
+
+        let from_addr = NibbleKey::from(ByteKey::from(from_bytes.clone()));
+        let to_addr = NibbleKey::from(ByteKey::from(to_bytes.clone()));
+        let from_key = state_key(&from_addr);
+        let to_key = state_key(&to_addr);
+
+        let sender_account = Account::Existing(from_addr, 1, U256::from(10u64), vec![], vec![]);
+        let entries = vec![(from_key.clone(), rlp::encode(&sender_account).to_vec())];
+        let (root, proof) = build_proof(&entries, &[from_key, to_key]);
+        let (_, storage_proof) = build_proof(&[], &[]);
+
+        let mut tx = Tx::new(from_bytes, to_bytes, 1);
+        tx.value = U256::from(30u64);
+        tx.sign(&[1u8; 32]);
+
+        let txdata = TxData {
+            proof,
+            storage_proof,
+            txs: vec![tx],
+            precompiles: default_precompiles(),
+        };
+
+        assert_eq!(txdata.apply(&root, 0), Err(ExecError::InsufficientBalance(0)));
+    }
+
+    #[test]
+    fn test_apply_self_transfer_bumps_nonce_so_replay_is_rejected() {
+        let addr_bytes = vec![
+            181, 154, 35, 232, 170, 166, 228, 13, 59, 214, 229, 236, 205, 9, 152, 122, 184, 20,
+            30, 197,
+        ];
+        let addr = NibbleKey::from(ByteKey::from(addr_bytes.clone()));
+        let key = state_key(&addr);
+
+        let account = Account::Existing(addr, 1, U256::from(100u64), vec![], vec![]);
+        let entries = vec![(key.clone(), rlp::encode(&account).to_vec())];
+        let (root, proof) = build_proof(&entries, &[key]);
+        let (_, storage_proof) = build_proof(&[], &[]);
+
+        // The identical signed self-transfer appears twice in the same
+        // batch. If the self-transfer leaf were clobbered by the stale
+        // pre-tx copy instead of merged, the nonce would never advance and
+        // the second occurrence would succeed too, minting free value.
+        let mut tx1 = Tx::new(addr_bytes.clone(), addr_bytes.clone(), 1);
+        tx1.value = U256::from(30u64);
+        tx1.sign(&[1u8; 32]);
+        let mut tx2 = Tx::new(addr_bytes.clone(), addr_bytes, 1);
+        tx2.value = U256::from(30u64);
+        tx2.sign(&[1u8; 32]);
+
+        let txdata = TxData {
+            proof,
+            storage_proof,
+            txs: vec![tx1, tx2],
+            precompiles: default_precompiles(),
+        };
+
+        assert_eq!(txdata.apply(&root, 0), Err(ExecError::BadNonce(1)));
+    }
+
     #[test]
     fn test_sign() {
         let skey = [1u8; 32];
@@ -265,7 +1578,115 @@ mod tests {
 
         tx.sign(&skey);
 
-        let (valid, _addr) = tx.sig_check();
+        let (valid, _addr) = tx.sig_check(0);
         assert!(valid);
     }
+
+    #[test]
+    fn test_verify_tx_signatures_dedups_by_digest_not_by_from_nonce() {
+        let from = vec![
+            181, 154, 35, 232, 170, 166, 228, 13, 59, 214, 229, 236, 205, 9, 152, 122, 184, 20,
+            30, 197,
+        ];
+
+        let mut tx1 = Tx::new(from.clone(), vec![6u8; 20], 1);
+        tx1.sign(&[1u8; 32]);
+
+        // Same `(from, nonce)` as tx1, but a different `to` and signed with a
+        // key that does not own `from`: its own signature must still be
+        // checked, not silently inherit tx1's recovered result.
+        let mut tx2 = Tx::new(from, vec![7u8; 20], 1);
+        tx2.sign(&[2u8; 32]);
+
+        assert_eq!(verify_tx_signatures(&[tx1, tx2], 0), Err(1));
+    }
+
+    #[test]
+    fn test_verify_tx_signatures_rejects_wrong_chain_id() {
+        let mut tx = Tx::new(
+            vec![
+                181, 154, 35, 232, 170, 166, 228, 13, 59, 214, 229, 236, 205, 9, 152, 122, 184,
+                20, 30, 197,
+            ],
+            vec![6u8; 20],
+            1,
+        );
+        tx.chain_id = 5;
+        tx.sign(&[1u8; 32]);
+
+        assert_eq!(verify_tx_signatures(&[tx], 1), Err(0));
+    }
+
+    #[test]
+    fn test_sign_wrong_chain_id() {
+        let skey = [1u8; 32];
+        let mut tx = Tx::new(
+            vec![
+                181, 154, 35, 232, 170, 166, 228, 13, 59, 214, 229, 236, 205, 9, 152, 122, 184, 20,
+                30, 197,
+            ],
+            vec![6u8; 20],
+            1,
+        );
+        tx.chain_id = 5;
+        tx.sign(&skey);
+
+        // A tx signed for chain 5 must not verify against chain 1.
+        let (valid, _addr) = tx.sig_check(1);
+        assert!(!valid);
+        let (valid, _addr) = tx.sig_check(5);
+        assert!(valid);
+    }
+
+    #[test]
+    fn test_range_proof_roundtrip() {
+        let mut tx = Tx::new(vec![1u8; 20], vec![2u8; 20], 0);
+        let blinding = scalar_from_u64(0xdead_beef);
+        let sender_blinding = scalar_from_u64(0xf00d);
+        tx.prove_range(
+            U256::from(42u64),
+            &blinding,
+            U256::from(100u64),
+            &sender_blinding,
+        )
+        .unwrap();
+
+        // A well-formed proof verifies, and tampering with the commitment
+        // breaks it.
+        assert!(tx.verify_range());
+        tx.value_commitment = Some(Commitment::new(U256::from(43u64), &blinding));
+        assert!(!tx.verify_range());
+    }
+
+    #[test]
+    fn test_balance_range_proof_catches_overspend() {
+        let value_blinding = scalar_from_u64(0xdead_beef);
+        let sender_blinding = scalar_from_u64(0xf00d);
+        let sender_balance = U256::from(100u64);
+
+        let mut tx = Tx::new(vec![1u8; 20], vec![2u8; 20], 0);
+        tx.prove_range(
+            U256::from(40u64),
+            &value_blinding,
+            sender_balance,
+            &sender_blinding,
+        )
+        .unwrap();
+
+        let from_old = Commitment::new(sender_balance, &sender_blinding);
+        let from_new = from_old.sub(tx.value_commitment.as_ref().unwrap()).unwrap();
+        assert!(tx.verify_balance_range(&from_new));
+
+        // A sender who doesn't actually hold the funds cannot produce a
+        // balance range proof at all.
+        let mut overspend = Tx::new(vec![1u8; 20], vec![2u8; 20], 0);
+        assert!(overspend
+            .prove_range(
+                U256::from(200u64),
+                &value_blinding,
+                sender_balance,
+                &sender_blinding,
+            )
+            .is_err());
+    }
 }